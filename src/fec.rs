@@ -0,0 +1,96 @@
+// Reed-Solomon forward error correction for the laser link.
+// A noisy optical path misreads whole pulses, not single bits, so shards (not
+// individual bits) are the unit of loss here: lose up to `m` of them and the
+// payload still reconstructs.
+
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+/// Data/parity shard counts for one transmission.
+///
+/// `k` data shards carry the payload; `m` parity shards let the receiver
+/// reconstruct the original from any `k` of the `k + m` shards that survive.
+#[derive(Clone, Copy)]
+pub struct FecConfig {
+    pub k: usize,
+    pub m: usize,
+}
+
+impl FecConfig {
+    pub fn new(k: usize, m: usize) -> FecConfig {
+        FecConfig { k, m }
+    }
+
+    pub fn total_shards(&self) -> usize {
+        self.k + self.m
+    }
+}
+
+impl Default for FecConfig {
+    /// 4 data shards, 2 parity shards: tolerates losing any 2 of 6.
+    fn default() -> FecConfig {
+        FecConfig { k: 4, m: 2 }
+    }
+}
+
+/// Outcome of a decode attempt. The receiver reports it alongside, not instead of,
+/// the Huffman decoder's checksum-based data-loss percentage.
+pub enum FecOutcome {
+    /// Every shard arrived intact; no reconstruction was needed.
+    Clean,
+    /// This many shards were erased and successfully rebuilt from parity.
+    Recovered(usize),
+    /// Fewer than `k` shards survived; the payload can't be recovered.
+    Unrecoverable,
+}
+
+/// Split `payload` into `config.k` equally sized data shards and append `config.m` parity shards.
+pub fn encode(payload: &[u8], config: FecConfig) -> Vec<Vec<u8>> {
+    let shard_len = (payload.len() + config.k - 1) / config.k.max(1);
+    let shard_len = shard_len.max(1);
+    let mut shards: Vec<Vec<u8>> = payload
+        .chunks(shard_len)
+        .map(|chunk| {
+            let mut shard = chunk.to_vec();
+            shard.resize(shard_len, 0);
+            shard
+        })
+        .collect();
+    while shards.len() < config.k {
+        shards.push(vec![0; shard_len]);
+    }
+    for _ in 0..config.m {
+        shards.push(vec![0; shard_len]);
+    }
+
+    let rs = ReedSolomon::new(config.k, config.m).expect("k and m form a valid code.");
+    rs.encode(&mut shards).expect("Shards are uniformly sized.");
+    shards
+}
+
+/// Reconstruct the original payload bytes from possibly-erased shards.
+///
+/// `shards[i] == None` marks a shard the receiver judged unreliable (its pulse
+/// lengths fell in the ambiguous bucket). `payload_len` is the original byte
+/// length before shard padding, used to trim the trailing zero bytes back off.
+pub fn decode(mut shards: Vec<Option<Vec<u8>>>, config: FecConfig, payload_len: usize) -> (FecOutcome, Vec<u8>) {
+    let present = shards.iter().filter(|shard| shard.is_some()).count();
+    if present < config.k {
+        return (FecOutcome::Unrecoverable, Vec::new());
+    }
+    let erased = config.total_shards() - present;
+
+    let rs = ReedSolomon::new(config.k, config.m).expect("k and m form a valid code.");
+    match rs.reconstruct(&mut shards) {
+        Ok(()) => {
+            let mut payload: Vec<u8> = shards
+                .into_iter()
+                .take(config.k)
+                .flat_map(|shard| shard.expect("Reconstructed."))
+                .collect();
+            payload.truncate(payload_len);
+            let outcome = if erased == 0 { FecOutcome::Clean } else { FecOutcome::Recovered(erased) };
+            (outcome, payload)
+        }
+        Err(_e) => (FecOutcome::Unrecoverable, Vec::new()),
+    }
+}
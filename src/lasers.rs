@@ -1,28 +1,82 @@
-use crate::huffman_code::HuffTree;
+use crate::fec::{self, FecConfig, FecOutcome};
+use crate::huffman_code::{HuffTree, PackedBits};
+use crate::merkle::{self, MerkleTree, Sibling};
 use gpiocdev::line::{Bias, Value::{Inactive, Active}};
 use gpiocdev::Request;
 use std::thread;
 use std::time::Duration;
 
-const LASER_PIN: u32 = 18;
-const RECEIVER_PIN: u32 = 23;
+/// Number of alternating short/long pulses sent during calibration.
+const CALIBRATION_PULSES: usize = 8;
+
+/// Hardware and timing profile for a laser link: which GPIO chip and lines to
+/// use, how long each kind of pulse is held, and the timestamp buckets the
+/// receiver uses to classify a pulse as short, long, ambiguous, or termination.
+#[derive(Clone)]
+pub struct LinkConfig {
+    pub chip_path: String,
+    pub laser_line: u32,
+    pub receiver_line: u32,
+    pub short_pulse: Duration,
+    pub long_pulse: Duration,
+    pub init_pulse: Duration,
+    pub term_pulse: Duration,
+    pub bit_gap: Duration,
+    pub init_bucket_min: u64,
+    pub init_bucket_max: u64,
+    pub short_bucket_max: u64,
+    pub long_bucket_max: u64,
+    pub ambiguous_bucket_max: u64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> LinkConfig {
+        LinkConfig {
+            chip_path: "/dev/gpiochip0".to_string(),
+            laser_line: 18,
+            receiver_line: 23,
+            short_pulse: Duration::from_micros(10),
+            long_pulse: Duration::from_micros(25),
+            init_pulse: Duration::from_micros(500),
+            term_pulse: Duration::from_micros(1000),
+            bit_gap: Duration::from_micros(50),
+            init_bucket_min: 401,
+            init_bucket_max: 900,
+            short_bucket_max: 89,
+            long_bucket_max: 199,
+            ambiguous_bucket_max: 1000,
+        }
+    }
+}
 
 pub struct Laser {
     out: Request,
-    encoded_message: Vec<u32>,
+    config: LinkConfig,
+    encoded_message: PackedBits,
 }
 
 pub struct Receiver {
     in_: Request,
-    huff_tree: HuffTree,
+    config: LinkConfig,
+}
+
+/// Raw bits collected from pulses, paired with per-bit reliability.
+///
+/// A bit is unreliable when its pulse length fell in the ambiguous bucket
+/// (above `long_bucket_max`, up to `ambiguous_bucket_max`; `200..=1000` with the
+/// default `LinkConfig`); its value is a guessed `0`, but `reliable[i]` stays
+/// `false` so shard framing can treat the whole shard carrying it as erased.
+struct RawReception {
+    bits: PackedBits,
+    reliable: Vec<bool>,
 }
 
 impl Laser {
-    pub fn new(encoded_message: Vec<u32>) -> Laser {
+    pub fn new(config: LinkConfig, encoded_message: PackedBits) -> Laser {
         // Open port for laser pin.
         let out = match Request::builder()
-            .on_chip("/dev/gpiochip0")
-            .with_line(LASER_PIN)
+            .on_chip(&config.chip_path)
+            .with_line(config.laser_line)
             .as_output(Inactive)
             .with_bias(Bias::PullUp)
             .request() {
@@ -31,62 +85,97 @@ impl Laser {
         };
         Self {
             out,
+            config,
             encoded_message,
         }
     }
 
-    /// Initiate message with 500 microsecond pulse.
+    /// Emit alternating short/long pulses so a receiver can measure actual observed
+    /// pulse lengths and auto-pick its thresholds via `Receiver::calibrate`.
+    pub fn send_calibration(&mut self) {
+        self.out.set_value(self.config.laser_line, Inactive).expect("Pin is on");
+        thread::sleep(self.config.bit_gap);
+        for i in 0..CALIBRATION_PULSES {
+            let width = if i % 2 == 0 { self.config.short_pulse } else { self.config.long_pulse };
+            self.out.set_value(self.config.laser_line, Active).expect("Pin is on");
+            thread::sleep(width);
+            self.out.set_value(self.config.laser_line, Inactive).expect("Pin is on");
+            thread::sleep(self.config.bit_gap);
+        }
+    }
+
+    /// Initiate message with `init_pulse`.
     ///
-    /// Transmit message; long pulse = 1 short pulse = 0.
+    /// Transmit message; `long_pulse` = 1, `short_pulse` = 0.
     ///
-    /// Terminate message with 1000 microsecond pulse.
+    /// Terminate message with `term_pulse`.
     pub fn send_message(&mut self) {
         // Initiation sequence.
-        self.out.set_value(LASER_PIN, Inactive).expect("Pin is on");
-        thread::sleep(Duration::from_micros(50));
-        self.out.set_value(LASER_PIN, Active).expect("Pin is on");
-        thread::sleep(Duration::from_micros(500));
-        self.out.set_value(LASER_PIN, Inactive).expect("Pin is on");
-        thread::sleep(Duration::from_micros(50));
+        self.out.set_value(self.config.laser_line, Inactive).expect("Pin is on");
+        thread::sleep(self.config.bit_gap);
+        self.out.set_value(self.config.laser_line, Active).expect("Pin is on");
+        thread::sleep(self.config.init_pulse);
+        self.out.set_value(self.config.laser_line, Inactive).expect("Pin is on");
+        thread::sleep(self.config.bit_gap);
 
         // Begin message transmission.
-        for bit in &self.encoded_message {
-            match *bit == 1 {
-                true => {
-                    self.out.set_value(LASER_PIN, Active).expect("Pin is on");
-                    thread::sleep(Duration::from_micros(25));
-                    self.out.set_value(LASER_PIN, Inactive).expect("Pin is on");
-                }
-                false => {
-                    self.out.set_value(LASER_PIN, Active).expect("Pin is on");
-                    thread::sleep(Duration::from_micros(10));
-                    self.out.set_value(LASER_PIN, Inactive).expect("Pin is on");
-                }
-            }
-            // Bit resolution. It gets sloppy below 50 microseconds.
-            thread::sleep(Duration::from_micros(50))
+        for bit in self.encoded_message.iter_bits() {
+            let width = if bit == 1 { self.config.long_pulse } else { self.config.short_pulse };
+            self.out.set_value(self.config.laser_line, Active).expect("Pin is on");
+            thread::sleep(width);
+            self.out.set_value(self.config.laser_line, Inactive).expect("Pin is on");
+            // Bit resolution. It gets sloppy below the configured gap.
+            thread::sleep(self.config.bit_gap)
         }
 
         // Termination sequence.
-        self.out.set_value(LASER_PIN, Active).expect("Pin is on");
-        thread::sleep(Duration::from_micros(1000));
-        self.out.set_value(LASER_PIN, Inactive).expect("Pin is on");
+        self.out.set_value(self.config.laser_line, Active).expect("Pin is on");
+        thread::sleep(self.config.term_pulse);
+        self.out.set_value(self.config.laser_line, Inactive).expect("Pin is on");
     }
 }
 
 impl Receiver {
-    pub fn new(huff_tree: HuffTree) -> Result<Receiver, gpiocdev::Error> {
+    pub fn new(config: LinkConfig) -> Result<Receiver, gpiocdev::Error> {
         // Open port for receiver pin.
         let in_ = match Request::builder()
-            .on_chip("/dev/gpiochip0")
-            .with_line(RECEIVER_PIN)
+            .on_chip(&config.chip_path)
+            .with_line(config.receiver_line)
             .as_input()
             .with_bias(Bias::PullUp)
             .request() {
             Ok(request) => request,
             Err(_e) => panic!()
         };
-        Ok(Self { in_, huff_tree })
+        Ok(Self { in_, config })
+    }
+
+    /// Measure `CALIBRATION_PULSES` incoming pulses and derive short/long bucket
+    /// thresholds from what the hardware actually produced. Lets the "gets sloppy
+    /// below 50 microseconds" floor drop on a cleaner optical path without editing
+    /// source, since the thresholds come from observed pulse lengths, not a guess.
+    ///
+    /// Leaves the configured thresholds untouched if too few pulses were seen.
+    pub fn calibrate(&mut self) {
+        let mut lengths = Vec::with_capacity(CALIBRATION_PULSES);
+        let events = self.in_.edge_events();
+        for event in events {
+            if let Ok(event) = event {
+                lengths.push(event.timestamp_ns);
+                if lengths.len() == CALIBRATION_PULSES {
+                    break;
+                }
+            }
+        }
+        if lengths.len() < CALIBRATION_PULSES {
+            return;
+        }
+        lengths.sort();
+        let short_observed = lengths[lengths.len() / 4];
+        let long_observed = lengths[3 * lengths.len() / 4];
+        let midpoint = short_observed + (long_observed - short_observed) / 2;
+        self.config.short_bucket_max = midpoint.saturating_sub(1);
+        self.config.long_bucket_max = long_observed + (long_observed - short_observed);
     }
 
     /// Loop until initiation sequence is detected.
@@ -95,10 +184,15 @@ impl Receiver {
             let events = self.in_.edge_events();
             for event in events {
                 match event {
-                    Ok(event) => match event.timestamp_ns {
-                        u64::MIN..=400 => continue,
-                        401..=900 => break,
-                        901.. => continue,
+                    Ok(event) => {
+                        let timestamp = event.timestamp_ns;
+                        if timestamp < self.config.init_bucket_min {
+                            continue;
+                        } else if timestamp <= self.config.init_bucket_max {
+                            break;
+                        } else {
+                            continue;
+                        }
                     }
                     Err(_e) => ()
                 }
@@ -107,23 +201,132 @@ impl Receiver {
     }
     /// Push 1 for long pulse, 0 for short.
     ///
+    /// An ambiguous pulse length no longer just drops silently: it still pushes a
+    /// guessed bit (so shard framing downstream doesn't desync) but is flagged
+    /// unreliable so the FEC layer can treat the shard carrying it as erased.
+    ///
     /// Return data upon termination sequence.
-    fn receive_message(&mut self) -> Vec<u32> {
-        let mut data = Vec::new();
+    fn receive_message(&mut self) -> RawReception {
+        let mut bits = PackedBits::new();
+        let mut reliable = Vec::new();
         let events = self.in_.edge_events();
         for event in events {
             match event {
-                Ok(event) => match event.timestamp_ns {
-                    u64::MIN..=0 => continue,
-                    1..=89 => data.push(0),
-                    90..=199 => data.push(1),
-                    200..=1000 => continue, // Bad data, we could guess, I guess?
-                    1001.. => break,        // Termination sequence.
+                Ok(event) => {
+                    let timestamp = event.timestamp_ns;
+                    if timestamp == 0 {
+                        continue;
+                    } else if timestamp <= self.config.short_bucket_max {
+                        bits.push_bit(0);
+                        reliable.push(true);
+                    } else if timestamp <= self.config.long_bucket_max {
+                        bits.push_bit(1);
+                        reliable.push(true);
+                    } else if timestamp <= self.config.ambiguous_bucket_max {
+                        bits.push_bit(0); // Ambiguous; guess and flag.
+                        reliable.push(false);
+                    } else {
+                        break; // Termination sequence.
+                    }
                 }
                 Err(_e) => continue
             }
         }
-        data
+        RawReception { bits, reliable }
+    }
+
+    /// Parse the header, Merkle root and FEC-framed shards out of a raw reception.
+    ///
+    /// Each shard is checked against the root via its sibling path before it's ever
+    /// handed to Reed-Solomon, so a shard that read as "reliable" pulse-timing-wise
+    /// but still got its bytes scrambled is caught and reported by index. The
+    /// reassembled payload then still passes through `decode_with_table`, which
+    /// applies the whole-message checksum check and appends its data-loss percentage.
+    fn decode_transmission(reception: &RawReception) -> String {
+        let (huff_tree, offset) = HuffTree::from_header(&reception.bits);
+
+        let k = reception.bits.read_byte(offset) as usize;
+        let m = reception.bits.read_byte(offset + 8) as usize;
+        let payload_len = u32::from_be_bytes([
+            reception.bits.read_byte(offset + 16),
+            reception.bits.read_byte(offset + 24),
+            reception.bits.read_byte(offset + 32),
+            reception.bits.read_byte(offset + 40),
+        ]) as usize;
+        let config = FecConfig::new(k, m);
+        let mut offset = offset + 48;
+
+        let mut root = [0_u8; merkle::ROOT_LEN];
+        for byte in root.iter_mut() {
+            *byte = reception.bits.read_byte(offset);
+            offset += 8;
+        }
+
+        let path_len = MerkleTree::path_len(config.total_shards());
+        let mut shards: Vec<Option<Vec<u8>>> = Vec::with_capacity(config.total_shards());
+        let mut corrupt_indices = Vec::new();
+        for shard_index in 0..config.total_shards() {
+            offset += 8; // Skip the shard index byte; shards arrive in order.
+            let shard_len = (u16::from_be_bytes([
+                reception.bits.read_byte(offset),
+                reception.bits.read_byte(offset + 8),
+            ])) as usize;
+            offset += 16;
+
+            let shard_bit_len = shard_len * 8;
+            // A dropped (not just garbled) pulse shortens the received bit stream
+            // below what the declared framing expects, so the offsets computed from
+            // header lengths can run past what actually arrived. Treat a shard whose
+            // window falls off the end of `reliable` as unreliable rather than
+            // indexing out of bounds; FEC then either reconstructs it from parity or
+            // reports `Unrecoverable`.
+            let shard_end = offset + shard_bit_len;
+            let pulse_reliable = shard_end <= reception.reliable.len()
+                && reception.reliable[offset..shard_end].iter().all(|bit_ok| *bit_ok);
+            let shard_bytes: Vec<u8> = (0..shard_len)
+                .map(|byte_index| reception.bits.read_byte(offset + byte_index * 8))
+                .collect();
+            offset += shard_bit_len;
+
+            let mut path = Vec::with_capacity(path_len);
+            for _ in 0..path_len {
+                let is_right = reception.bits.read_byte(offset) != 0;
+                offset += 8;
+                let mut sibling_hash = [0_u8; merkle::ROOT_LEN];
+                for byte in sibling_hash.iter_mut() {
+                    *byte = reception.bits.read_byte(offset);
+                    offset += 8;
+                }
+                path.push(if is_right { Sibling::Right(sibling_hash) } else { Sibling::Left(sibling_hash) });
+            }
+
+            let verified = pulse_reliable && merkle::verify(&shard_bytes, &path, &root);
+            if !verified {
+                corrupt_indices.push(shard_index);
+            }
+            shards.push(if verified { Some(shard_bytes) } else { None });
+        }
+
+        let (outcome, payload) = fec::decode(shards, config, payload_len);
+        let corruption_report = if corrupt_indices.is_empty() {
+            "All chunks verified against the Merkle root.".to_string()
+        } else {
+            format!("Corrupt chunk indices: {corrupt_indices:?}")
+        };
+        let status = match outcome {
+            FecOutcome::Clean => "Clean receive, no FEC reconstruction needed.".to_string(),
+            FecOutcome::Recovered(erased) => format!("Recovered {erased} erased shard(s) via Reed-Solomon."),
+            FecOutcome::Unrecoverable => {
+                return format!("Error: too many shards lost to reconstruct message.\n{corruption_report}\n");
+            }
+        };
+
+        let mut payload_bits = PackedBits::new();
+        for byte in payload {
+            payload_bits.push_byte(byte);
+        }
+        // Table-driven decode keeps this hot loop cheap while pulses are still being timed.
+        format!("{status}\n{corruption_report}\n{}", huff_tree.decode_with_table(&payload_bits))
     }
 
     /// Call detect, receive and decode methods.
@@ -135,8 +338,8 @@ impl Receiver {
         let start = chrono::Utc::now();
 
         println!("\nIncoming message detected...\n");
-        let data = self.receive_message();
-        let message = self.huff_tree.decode(&data);
+        let reception = self.receive_message();
+        let message = Self::decode_transmission(&reception);
 
         // Calculate stats
         let num_kbytes = message.len() as f64 / 1000.0;
@@ -151,18 +354,84 @@ impl Receiver {
     }
 }
 
+/// Frame the Merkle root and FEC shards after the canonical-code header: k, m,
+/// payload byte length, the 32-byte root, then each shard as (index, length,
+/// bytes, sibling path). Mirrors `decode_transmission`'s parsing.
+fn build_transmission(huff_tree: &HuffTree, encoded_message: &PackedBits, config: FecConfig) -> PackedBits {
+    let mut transmission = huff_tree.encode_header();
+
+    let payload_bytes = encoded_message.to_bytes();
+    let shards = fec::encode(&payload_bytes, config);
+    let merkle_tree = MerkleTree::build(&shards);
+
+    transmission.push_byte(config.k as u8);
+    transmission.push_byte(config.m as u8);
+    for byte in (payload_bytes.len() as u32).to_be_bytes() {
+        transmission.push_byte(byte);
+    }
+    for byte in merkle_tree.root() {
+        transmission.push_byte(byte);
+    }
+
+    for (index, shard) in shards.iter().enumerate() {
+        transmission.push_byte(index as u8);
+        for byte in (shard.len() as u16).to_be_bytes() {
+            transmission.push_byte(byte);
+        }
+        for byte in shard {
+            transmission.push_byte(*byte);
+        }
+        for sibling in merkle_tree.path(index) {
+            let (is_right, hash) = match sibling {
+                Sibling::Right(hash) => (1_u8, hash),
+                Sibling::Left(hash) => (0_u8, hash),
+            };
+            transmission.push_byte(is_right);
+            for byte in hash {
+                transmission.push_byte(byte);
+            }
+        }
+    }
+    transmission
+}
+
 /// Send a message with a laser!
 pub fn do_laser(message: String) {
+    do_laser_with_config(message, FecConfig::default(), LinkConfig::default())
+}
+
+/// Send a message with a laser, trading bandwidth for resilience via `fec_config`.
+pub fn do_laser_with_fec(message: String, fec_config: FecConfig) {
+    do_laser_with_config(message, fec_config, LinkConfig::default())
+}
+
+/// Send a message with a laser over a link described by `link_config`, after
+/// letting the receiver calibrate its pulse-length thresholds against it.
+pub fn do_laser_with_config(message: String, fec_config: FecConfig, link_config: LinkConfig) {
     // Compress message with Huffman Coding.
     let mut huff_tree = HuffTree::new();
     let encoded_message = huff_tree.encode(message);
 
-    // Pass huff_tree to receiver to decode message.
-    let mut receiver = match Receiver::new(huff_tree) {
+    // Frame the canonical-code header and FEC shards so the receiver is
+    // self-describing and can repair dropped/garbled pulses.
+    let transmission = build_transmission(&huff_tree, &encoded_message, fec_config);
+
+    let mut receiver = match Receiver::new(link_config.clone()) {
         Ok(receiver) => receiver,
         Err(_e) => panic!()
     };
-    let mut laser = Laser::new(encoded_message);
+    let mut laser = Laser::new(link_config, transmission);
+
+    // Calibrate the receiver's thresholds against this link before the real traffic
+    // starts. Run the burst and the measurement concurrently, same as the real
+    // message exchange below: calling these sequentially risked the whole burst
+    // firing (and its edge events being dropped) before the receiver started
+    // reading them.
+    thread::scope(|scope| {
+        let calibration = scope.spawn(|| receiver.calibrate());
+        laser.send_calibration();
+        calibration.join().expect("Calibration thread closes");
+    });
 
     // Start a thread each for the laser and receiver.
     let receiver_thread = thread::Builder::new()
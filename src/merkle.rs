@@ -0,0 +1,90 @@
+// Binary Merkle tree over a message's fixed-size blocks.
+// https://en.wikipedia.org/wiki/Merkle_tree
+// Structure mirrors the roughenough project's merkle module: a tree of hashes
+// with sibling-path proofs so a single block can be verified against the root
+// without re-hashing the whole message.
+
+use sha2::{Digest, Sha256};
+
+pub const ROOT_LEN: usize = 32;
+
+fn hash_leaf(block: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]); // Leaf domain tag, keeps leaf and node hashes from colliding.
+    hasher.update(block);
+    hasher.finalize().into()
+}
+
+fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]); // Internal-node domain tag.
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// One step of a sibling path: the neighboring hash and which side it sits on.
+#[derive(Clone, Copy)]
+pub enum Sibling {
+    Left([u8; 32]),
+    Right([u8; 32]),
+}
+
+/// A binary Merkle tree over a fixed set of blocks, one leaf per block.
+pub struct MerkleTree {
+    levels: Vec<Vec<[u8; 32]>>, // levels[0] = leaf hashes, levels.last() = [root].
+}
+
+impl MerkleTree {
+    /// Build a tree over `blocks`. An odd-sized level duplicates its last node,
+    /// the usual convention for an unbalanced number of leaves.
+    pub fn build(blocks: &[Vec<u8>]) -> MerkleTree {
+        let mut levels = vec![blocks.iter().map(|block| hash_leaf(block)).collect::<Vec<_>>()];
+        while levels.last().expect("At least one level.").len() > 1 {
+            let prev = levels.last().expect("At least one level.");
+            let next = prev
+                .chunks(2)
+                .map(|pair| hash_node(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+                .collect();
+            levels.push(next);
+        }
+        MerkleTree { levels }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        *self.levels.last().expect("At least one level.").first().expect("Root exists.")
+    }
+
+    /// Number of sibling-path steps from a leaf to the root, given the leaf count.
+    /// Both sender and receiver derive this the same way, so it never needs transmitting.
+    pub fn path_len(leaf_count: usize) -> usize {
+        let mut n = leaf_count;
+        let mut steps = 0;
+        while n > 1 {
+            n = (n + 1) / 2;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Sibling path from leaf `index` up to (not including) the root.
+    pub fn path(&self, mut index: usize) -> Vec<Sibling> {
+        let mut path = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling_index = if index % 2 == 0 { index + 1 } else { index - 1 };
+            let sibling_hash = *level.get(sibling_index).unwrap_or(&level[index]);
+            path.push(if index % 2 == 0 { Sibling::Right(sibling_hash) } else { Sibling::Left(sibling_hash) });
+            index /= 2;
+        }
+        path
+    }
+}
+
+/// Verify `block` against `root` using its sibling `path`.
+pub fn verify(block: &[u8], path: &[Sibling], root: &[u8; 32]) -> bool {
+    let hash = path.iter().fold(hash_leaf(block), |hash, sibling| match sibling {
+        Sibling::Left(left) => hash_node(left, &hash),
+        Sibling::Right(right) => hash_node(&hash, right),
+    });
+    &hash == root
+}